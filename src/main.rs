@@ -1,18 +1,29 @@
-use std::{collections::VecDeque, iter::zip};
+use std::{
+    collections::{HashSet, VecDeque},
+    fs,
+    iter::zip,
+};
 
 use bevy::{
-    prelude::*, sprite::MaterialMesh2dBundle, time::common_conditions::on_timer, utils::Duration,
+    ecs::system::SystemParam, prelude::*, sprite::MaterialMesh2dBundle, utils::Duration,
+    window::PrimaryWindow,
 };
 
 use rand::{seq::IteratorRandom, thread_rng};
 
 const FONT: &'static str = "fonts/FiraMonoNerdFont-Bold.otf";
+const HIGH_SCORE_FILE: &'static str = "high_score.txt";
 
 const STEP: i16 = 10;
 const WALL_SIZE: i16 = 200;
-const WALL_POS: f32 = (WALL_SIZE + STEP) as f32;
-const STRETCH: f32 = 2. * WALL_POS;
-const THICKNESS: f32 = 5.;
+// Thickness of a border wall, in the same cell units as `Size`.
+const WALL_THICKNESS: f32 = 0.2;
+
+// Size of the playable grid in cells, derived from the pixel wall/step sizes
+// so the board keeps the same footprint it always has, just addressed by
+// integer cell instead of raw pixels.
+const ARENA_WIDTH: i32 = (2 * WALL_SIZE / STEP) as i32;
+const ARENA_HEIGHT: i32 = ARENA_WIDTH;
 
 const SCORE_SIZE: f32 = 20.;
 const SCOREBOARD_FONT_SIZE: f32 = 40.0;
@@ -21,17 +32,36 @@ const SCORE_COLOR: Color = Color::RED;
 const SNAKE_COLOR: Color = Color::GREEN;
 const WALL_COLOR: Color = Color::BLUE;
 
+// Fraction of a grid cell a sprite/AABB occupies, leaving a sliver of gap between cells.
+const SPRITE_SCALE: f32 = 0.8;
+
+const FOOD_SPAWN_INTERVAL: f32 = 1.5;
+const FOOD_CAP: usize = 3;
+
 #[derive(Resource)]
 struct Scoreboard {
     score: usize,
 }
 
+#[derive(Resource, Debug, Default)]
+struct HighScore(usize);
+
 #[derive(Component)]
 struct ScoreText;
 
+#[derive(Component)]
+struct GameOverScreen;
+
+#[derive(Component)]
+struct LevelSelectScreen;
+
+#[derive(Component)]
+struct LevelSelectText;
+
 #[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States, SystemSet)]
 enum AppState {
     #[default]
+    SelectLevel,
     InGame,
     Lost,
 }
@@ -43,18 +73,77 @@ struct Body;
 struct Head;
 
 #[derive(Component, Debug)]
-struct Apple;
+struct Food;
+
+#[derive(Component, Debug)]
+struct Obstacle;
+
+#[derive(Component, Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
+struct Position {
+    x: i32,
+    y: i32,
+}
 
-#[derive(Debug, Default, Copy, Clone)]
-struct Direction {
-    x: i16,
-    y: i16,
+#[derive(Component)]
+struct Size {
+    width: f32,
+    height: f32,
+}
+
+impl Size {
+    fn square(side: f32) -> Self {
+        Self {
+            width: side,
+            height: side,
+        }
+    }
+}
+
+// A border wall doesn't occupy a single grid cell like `Position` does; it sits on the
+// boundary between cells, so it's addressed with fractional cell coordinates instead.
+#[derive(Component)]
+struct WallEdge {
+    x: f32,
+    y: f32,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum Direction {
+    Left,
+    Up,
+    #[default]
+    Right,
+    Down,
+}
+
+impl Direction {
+    fn opposite(self) -> Self {
+        match self {
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+        }
+    }
+
+    fn delta(self) -> (i32, i32) {
+        match self {
+            Direction::Left => (-1, 0),
+            Direction::Right => (1, 0),
+            Direction::Up => (0, 1),
+            Direction::Down => (0, -1),
+        }
+    }
 }
 
 #[derive(Resource, Debug, Default)]
 struct Snake {
     ids: Vec<Entity>,
     dirs: VecDeque<Direction>,
+    // The head's committed heading, and the latest turn request waiting to
+    // be committed at the start of the next movement tick.
+    heading: Direction,
+    pending: Direction,
 }
 
 impl Snake {
@@ -72,45 +161,249 @@ impl Snake {
 #[derive(Resource, Debug, Default)]
 struct SnakeLength(usize);
 
+#[derive(Event)]
+struct GrowthEvent;
+
+#[derive(Resource, Default)]
+struct LastTailPosition(Option<Position>);
+
+#[derive(Resource)]
+struct FoodSpawnTimer(Timer);
+
+impl Default for FoodSpawnTimer {
+    fn default() -> Self {
+        Self(Timer::from_seconds(FOOD_SPAWN_INTERVAL, TimerMode::Repeating))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LevelLayout {
+    Open,
+    Cross,
+    Ring,
+}
+
+impl LevelLayout {
+    const ALL: [LevelLayout; 3] = [LevelLayout::Open, LevelLayout::Cross, LevelLayout::Ring];
+
+    fn label(self) -> &'static str {
+        match self {
+            LevelLayout::Open => "Open",
+            LevelLayout::Cross => "Cross",
+            LevelLayout::Ring => "Ring",
+        }
+    }
+
+    fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&layout| layout == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    fn prev(self) -> Self {
+        let index = Self::ALL.iter().position(|&layout| layout == self).unwrap();
+        Self::ALL[(index + Self::ALL.len() - 1) % Self::ALL.len()]
+    }
+
+    fn build(self, config: &GameConfig) -> Level {
+        let cells = match self {
+            LevelLayout::Open => Vec::new(),
+            LevelLayout::Cross => {
+                let mid_x = config.arena_width / 2;
+                let mid_y = config.arena_height / 2;
+                let mut cells = Vec::new();
+                cells.extend((0..config.arena_width).map(|x| Position { x, y: mid_y }));
+                cells.extend((0..config.arena_height).map(|y| Position { x: mid_x, y }));
+                cells
+            }
+            LevelLayout::Ring => {
+                const MARGIN: i32 = 4;
+                let min_x = MARGIN;
+                let max_x = config.arena_width - MARGIN - 1;
+                let min_y = MARGIN;
+                let max_y = config.arena_height - MARGIN - 1;
+                let mut cells = Vec::new();
+                for x in min_x..=max_x {
+                    cells.push(Position { x, y: min_y });
+                    cells.push(Position { x, y: max_y });
+                }
+                for y in min_y..=max_y {
+                    cells.push(Position { x: min_x, y });
+                    cells.push(Position { x: max_x, y });
+                }
+                cells
+            }
+        };
+
+        // The loops above can generate overlapping cells (e.g. a Ring's corners, where
+        // both the x- and y-sweep push the same Position); dedupe so each occupied cell
+        // spawns exactly one Obstacle entity.
+        let mut seen = HashSet::new();
+        Level {
+            cells: cells.into_iter().filter(|cell| seen.insert(*cell)).collect(),
+        }
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+struct Level {
+    cells: Vec<Position>,
+}
+
+#[derive(Resource, Debug, Clone)]
+struct GameConfig {
+    start_interval: f32,
+    min_interval: f32,
+    apples_per_speedup: usize,
+    arena_width: i32,
+    arena_height: i32,
+    layout: LevelLayout,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            start_interval: 0.15,
+            min_interval: 0.05,
+            apples_per_speedup: 20,
+            arena_width: ARENA_WIDTH,
+            arena_height: ARENA_HEIGHT,
+            layout: LevelLayout::Open,
+        }
+    }
+}
+
+#[derive(Resource)]
+struct MoveTimer(Timer);
+
+impl MoveTimer {
+    fn from_interval(interval: f32) -> Self {
+        Self(Timer::from_seconds(interval, TimerMode::Repeating))
+    }
+}
+
 fn main() {
     let mut app = App::new();
 
     app.add_plugins(DefaultPlugins);
 
+    let config = GameConfig::default();
+    let level = config.layout.build(&config);
+
     app.insert_resource(ClearColor(Color::BLACK));
     app.insert_resource(Snake::default());
     app.insert_resource(Scoreboard { score: 0 });
+    app.insert_resource(HighScore::default());
+    app.insert_resource(LastTailPosition::default());
+    app.insert_resource(FoodSpawnTimer::default());
+    app.insert_resource(MoveTimer::from_interval(config.start_interval));
+    app.insert_resource(level);
+    app.insert_resource(config);
+    app.add_event::<GrowthEvent>();
 
-    app.add_systems(Startup, (setup, setup_items));
+    app.add_systems(Startup, (setup, load_high_score));
 
     app.add_state::<AppState>()
+        .add_systems(OnEnter(AppState::SelectLevel), show_level_select)
+        .add_systems(Update, select_level.run_if(in_state(AppState::SelectLevel)))
+        .add_systems(
+            Update,
+            (
+                despawn_level_select_screen,
+                apply_level_selection.after(despawn_level_select_screen),
+                setup_items.after(apply_level_selection),
+                enter_game.after(setup_items),
+            )
+                .run_if(in_state(AppState::SelectLevel))
+                .run_if(confirm_pressed),
+        )
         .add_systems(
             Update,
             (
                 wall_collision,
                 change_direction.after(wall_collision),
-                move_snake
-                    .run_if(on_timer(Duration::from_secs_f32(0.10)))
-                    .before(change_direction)
-                    .after(wall_collision),
-                eat_apple.after(move_snake),
+                move_snake.before(change_direction).after(wall_collision),
+                adjust_move_speed.after(move_snake),
+                snake_eating.after(move_snake),
+                snake_growth.after(snake_eating),
+                food_spawner,
                 check_lost.after(move_snake),
             )
                 .run_if(in_state(AppState::InGame)),
         )
+        .add_systems(
+            OnEnter(AppState::Lost),
+            (record_high_score, show_game_over.after(record_high_score)),
+        )
         .add_systems(
             Update,
             (
+                despawn_game_over_screen.before(clear_map),
                 clear_map.before(setup_items),
                 setup_items.after(clear_map),
                 enter_game.after(clear_map).after(setup_items),
             )
-                .run_if(in_state(AppState::Lost)),
+                .run_if(in_state(AppState::Lost))
+                .run_if(confirm_pressed),
         );
 
+    app.add_systems(
+        PostUpdate,
+        (position_translation, wall_translation, size_scaling),
+    );
+
     app.run();
 }
 
+fn convert(pos: f32, bound_window: f32, bound_game: f32) -> f32 {
+    let tile_size = bound_window / bound_game;
+    pos / bound_game * bound_window - (bound_window / 2.) + (tile_size / 2.)
+}
+
+fn position_translation(
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut query: Query<(&Position, &mut Transform)>,
+    config: Res<GameConfig>,
+) {
+    let window = window_query.single();
+    for (pos, mut transform) in query.iter_mut() {
+        transform.translation = Vec3::new(
+            convert(pos.x as f32, window.width(), config.arena_width as f32),
+            convert(pos.y as f32, window.height(), config.arena_height as f32),
+            transform.translation.z,
+        );
+    }
+}
+
+fn size_scaling(
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut query: Query<(&Size, &mut Transform)>,
+    config: Res<GameConfig>,
+) {
+    let window = window_query.single();
+    for (size, mut transform) in query.iter_mut() {
+        transform.scale = Vec3::new(
+            size.width / config.arena_width as f32 * window.width(),
+            size.height / config.arena_height as f32 * window.height(),
+            1.0,
+        );
+    }
+}
+
+fn wall_translation(
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut query: Query<(&WallEdge, &mut Transform)>,
+    config: Res<GameConfig>,
+) {
+    let window = window_query.single();
+    for (edge, mut transform) in query.iter_mut() {
+        transform.translation = Vec3::new(
+            convert(edge.x, window.width(), config.arena_width as f32),
+            convert(edge.y, window.height(), config.arena_height as f32),
+            transform.translation.z,
+        );
+    }
+}
+
 fn get_square(
     meshes: &mut ResMut<Assets<Mesh>>,
     materials: &mut ResMut<Assets<ColorMaterial>>,
@@ -118,7 +411,6 @@ fn get_square(
 ) -> MaterialMesh2dBundle<ColorMaterial> {
     MaterialMesh2dBundle {
         mesh: meshes.add(Mesh::from(shape::Quad::default())).into(),
-        transform: Transform::default().with_scale(Vec3::splat(STEP as f32)),
         material: materials.add(ColorMaterial::from(color)),
         ..Default::default()
     }
@@ -129,81 +421,212 @@ fn setup_items(
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
     mut snake: ResMut<Snake>,
+    config: Res<GameConfig>,
+    level: Res<Level>,
 ) {
     let square = get_square(&mut meshes, &mut materials, SNAKE_COLOR);
-    let wall_size = WALL_SIZE as f32;
-    let step = STEP as f32;
 
-    // spawn snake in lower right corner
+    let obstacles: HashSet<Position> = level.cells.iter().copied().collect();
+
+    // Body length, including the head, that the snake always spawns with.
+    let body_len = (WALL_SIZE as f32 / 6.).round() as i32;
+
+    // Spawn in the lower right corner, walking down a row at a time, until the whole
+    // body fits without landing on an obstacle cell (e.g. a Cross layout's mid column
+    // otherwise crosses right through the fixed y = 0 spawn row).
+    let spawn_y = (0..config.arena_height)
+        .find(|&y| {
+            (1..body_len).all(|i| {
+                !obstacles.contains(&Position {
+                    x: config.arena_width - i,
+                    y,
+                })
+            })
+        })
+        .unwrap_or(0);
+
+    let head_pos = Position {
+        x: config.arena_width - 1,
+        y: spawn_y,
+    };
     let head_id = commands
-        .spawn((
-            Body {},
-            Head {},
-            SpatialBundle {
-                transform: Transform::from_xyz(wall_size - step, -wall_size, 1.),
-                ..Default::default()
-            },
-        ))
+        .spawn((Body {}, Head {}, head_pos, SpatialBundle::default()))
         .with_children(|parent| {
             // spawn a square shape as snake head
-            parent.spawn(square.clone());
+            parent.spawn((square.clone(), Size::square(SPRITE_SCALE)));
         })
         .id();
 
-    let dir = Direction { x: STEP, y: 0 };
+    let dir = Direction::default();
+    snake.heading = dir;
+    snake.pending = dir;
     snake.add_entity(head_id, dir);
 
+    let mut occupied = vec![head_pos];
+
     // spawn square shapes as snake body
-    for i in 2..((wall_size / 6.).round() as usize) {
+    for i in 2..body_len {
+        let tail_pos = Position {
+            x: config.arena_width - i,
+            y: spawn_y,
+        };
         let tail_id = commands
-            .spawn((
-                Body {},
-                SpatialBundle {
-                    transform: Transform::from_xyz(wall_size - i as f32 * step, -wall_size, 1.),
-                    ..Default::default()
-                },
-            ))
+            .spawn((Body {}, tail_pos, SpatialBundle::default()))
             .with_children(|parent| {
-                parent.spawn(square.clone());
+                parent.spawn((square.clone(), Size::square(SPRITE_SCALE)));
             })
             .id();
         snake.add_entity(tail_id, dir);
+        occupied.push(tail_pos);
     }
 
     let red_square = get_square(&mut meshes, &mut materials, Color::RED);
 
-    // spawn apple in center
+    // spawn the first food item as close to the center as possible, without landing on
+    // an obstacle or the snake; food_spawner tops up the rest later
+    let center = Position {
+        x: config.arena_width / 2,
+        y: config.arena_height / 2,
+    };
+    // A ring's radius can't usefully exceed the arena's Chebyshev span; beyond that every
+    // cell is out of bounds, so bound the search instead of assuming a free cell exists.
+    let max_radius = config.arena_width.max(config.arena_height);
+    let food_pos = (0..=max_radius)
+        .flat_map(|radius| ring_around(center, radius, &config))
+        .find(|pos| !obstacles.contains(pos) && !occupied.contains(pos))
+        .unwrap_or(center);
+    occupied.push(food_pos);
     commands
-        .spawn((Apple {}, SpatialBundle::default()))
+        .spawn((Food {}, food_pos, SpatialBundle::default()))
         .with_children(|parent| {
-            parent.spawn(red_square);
+            parent.spawn((red_square, Size::square(SPRITE_SCALE)));
+        });
+
+    // spawn obstacles for the active level; the snake and food spawns above were chosen
+    // to avoid these cells rather than the other way around
+    let obstacle_square = get_square(&mut meshes, &mut materials, WALL_COLOR);
+    for &cell in level.cells.iter() {
+        commands
+            .spawn((Obstacle, cell, SpatialBundle::default()))
+            .with_children(|parent| {
+                parent.spawn((obstacle_square.clone(), Size::square(SPRITE_SCALE)));
+            });
+    }
+}
+
+/// Cells at exactly Chebyshev distance `radius` from `center`, clipped to the arena.
+/// `radius == 0` yields just `center`.
+fn ring_around(center: Position, radius: i32, config: &GameConfig) -> Vec<Position> {
+    let in_bounds = |pos: &Position| {
+        pos.x >= 0 && pos.x < config.arena_width && pos.y >= 0 && pos.y < config.arena_height
+    };
+
+    if radius == 0 {
+        return vec![center].into_iter().filter(|p| in_bounds(p)).collect();
+    }
+
+    let mut cells = Vec::new();
+    for x in (center.x - radius)..=(center.x + radius) {
+        cells.push(Position {
+            x,
+            y: center.y - radius,
+        });
+        cells.push(Position {
+            x,
+            y: center.y + radius,
+        });
+    }
+    for y in (center.y - radius + 1)..(center.y + radius) {
+        cells.push(Position {
+            x: center.x - radius,
+            y,
+        });
+        cells.push(Position {
+            x: center.x + radius,
+            y,
         });
+    }
+    cells.retain(in_bounds);
+    cells
 }
 
-fn setup(mut commands: Commands, asset_server: Res<AssetServer>, scoreboard: Res<Scoreboard>) {
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    scoreboard: Res<Scoreboard>,
+    config: Res<GameConfig>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+) {
     commands.spawn(Camera2dBundle::default());
 
-    let wall = |position: Vec2, size: Vec2| SpriteBundle {
-        transform: Transform {
-            translation: position.extend(0.0),
-            scale: size.extend(1.0),
-            ..Default::default()
-        },
-        sprite: Sprite {
-            color: WALL_COLOR,
-            ..Default::default()
-        },
-        ..Default::default()
+    // Border walls sit just outside the last valid cell on each axis, so they track
+    // `wall_collision`'s wrap boundary through `wall_translation`/`size_scaling` at any
+    // window size instead of a fixed pixel box.
+    let left_edge = -0.5;
+    let right_edge = config.arena_width as f32 - 0.5;
+    let bottom_edge = -0.5;
+    let top_edge = config.arena_height as f32 - 0.5;
+    let mid_x = (config.arena_width - 1) as f32 / 2.0;
+    let mid_y = (config.arena_height - 1) as f32 / 2.0;
+
+    let wall = |edge: WallEdge, size: Size| {
+        (
+            edge,
+            size,
+            SpriteBundle {
+                sprite: Sprite {
+                    color: WALL_COLOR,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
     };
 
-    // Spawn square walls
-    let hor_wall = Vec2::new(STRETCH + THICKNESS, THICKNESS);
-    let vert_wall = Vec2::new(THICKNESS, STRETCH + THICKNESS);
-    commands.spawn(wall(Vec2::new(0., WALL_POS), hor_wall)); // top
-    commands.spawn(wall(Vec2::new(0., -WALL_POS), hor_wall)); // bottom
-    commands.spawn(wall(Vec2::new(WALL_POS, 0.), vert_wall)); // right
-    commands.spawn(wall(Vec2::new(-WALL_POS, 0.), vert_wall)); // left
+    let horizontal = Size {
+        width: config.arena_width as f32,
+        height: WALL_THICKNESS,
+    };
+    let vertical = Size {
+        width: WALL_THICKNESS,
+        height: config.arena_height as f32,
+    };
+    commands.spawn(wall(
+        WallEdge {
+            x: mid_x,
+            y: top_edge,
+        },
+        horizontal,
+    ));
+    commands.spawn(wall(
+        WallEdge {
+            x: mid_x,
+            y: bottom_edge,
+        },
+        Size {
+            width: config.arena_width as f32,
+            height: WALL_THICKNESS,
+        },
+    ));
+    commands.spawn(wall(
+        WallEdge {
+            x: right_edge,
+            y: mid_y,
+        },
+        vertical,
+    ));
+    commands.spawn(wall(
+        WallEdge {
+            x: left_edge,
+            y: mid_y,
+        },
+        Size {
+            width: WALL_THICKNESS,
+            height: config.arena_height as f32,
+        },
+    ));
 
+    let window = window_query.single();
     commands.spawn((
         ScoreText,
         Text2dBundle {
@@ -218,7 +641,7 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>, scoreboard: Res
                 )],
                 ..Default::default()
             },
-            transform: Transform::from_xyz(0., WALL_POS + SCORE_SIZE + STEP as f32 + THICKNESS, 1.)
+            transform: Transform::from_xyz(0., window.height() / 2. + SCORE_SIZE, 1.)
                 .with_scale(Vec3::splat(1.0)),
             ..Default::default()
         },
@@ -227,164 +650,400 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>, scoreboard: Res
 
 fn check_lost(
     mut app_state: ResMut<NextState<AppState>>,
-    head_query: Query<&Transform, With<Head>>,
-    body_pos_query: Query<&Transform, (With<Body>, Without<Head>)>,
+    head_query: Query<&Position, With<Head>>,
+    body_pos_query: Query<&Position, (With<Body>, Without<Head>)>,
+    obstacle_query: Query<&Position, With<Obstacle>>,
 ) {
     let head_pos = head_query.single();
     for body_pos in body_pos_query.iter() {
-        let diff = (head_pos.translation - body_pos.translation).abs();
-        if diff.x <= f32::EPSILON && diff.y <= f32::EPSILON {
+        if head_pos == body_pos {
+            app_state.set(AppState::Lost);
+            return;
+        }
+    }
+
+    // Compare the live grid Position rather than Transform, which position_translation
+    // only syncs in PostUpdate, a frame behind this tick's move_snake. This replaced an
+    // earlier `collide_aabb` check that shared its routine with the border walls, but
+    // the border walls don't go through a collision check at all (`wall_collision` just
+    // wraps the position), so that unification never actually applied to them.
+    for obstacle_pos in obstacle_query.iter() {
+        if head_pos == obstacle_pos {
             app_state.set(AppState::Lost);
             return;
         }
     }
 }
 
+fn load_high_score(mut high_score: ResMut<HighScore>) {
+    if let Ok(contents) = fs::read_to_string(HIGH_SCORE_FILE) {
+        if let Ok(score) = contents.trim().parse() {
+            high_score.0 = score;
+        }
+    }
+}
+
+fn record_high_score(scoreboard: Res<Scoreboard>, mut high_score: ResMut<HighScore>) {
+    if scoreboard.score > high_score.0 {
+        high_score.0 = scoreboard.score;
+        let _ = fs::write(HIGH_SCORE_FILE, high_score.0.to_string());
+    }
+}
+
+fn show_game_over(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    scoreboard: Res<Scoreboard>,
+    high_score: Res<HighScore>,
+) {
+    let text_style = |font_size, color| TextStyle {
+        font: asset_server.load(FONT),
+        font_size,
+        color,
+    };
+
+    commands
+        .spawn((
+            GameOverScreen,
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(10.),
+                    ..Default::default()
+                },
+                background_color: Color::rgba(0., 0., 0., 0.75).into(),
+                ..Default::default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Game Over",
+                text_style(60., SCORE_COLOR),
+            ));
+            parent.spawn(TextBundle::from_section(
+                format!("Score: {}", scoreboard.score),
+                text_style(SCOREBOARD_FONT_SIZE, Color::WHITE),
+            ));
+            parent.spawn(TextBundle::from_section(
+                format!("High score: {}", high_score.0),
+                text_style(SCOREBOARD_FONT_SIZE, Color::WHITE),
+            ));
+            parent.spawn(TextBundle::from_section(
+                "Press Space to restart",
+                text_style(SCORE_SIZE, Color::WHITE),
+            ));
+        });
+}
+
+fn confirm_pressed(keyboard_input: Res<Input<KeyCode>>) -> bool {
+    keyboard_input.just_pressed(KeyCode::Space) || keyboard_input.just_pressed(KeyCode::Return)
+}
+
+fn show_level_select(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<GameConfig>,
+) {
+    let text_style = |font_size, color| TextStyle {
+        font: asset_server.load(FONT),
+        font_size,
+        color,
+    };
+
+    commands
+        .spawn((
+            LevelSelectScreen,
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(10.),
+                    ..Default::default()
+                },
+                background_color: Color::BLACK.into(),
+                ..Default::default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Select a level",
+                text_style(60., SCORE_COLOR),
+            ));
+            parent.spawn((
+                LevelSelectText,
+                TextBundle::from_section(
+                    config.layout.label(),
+                    text_style(SCOREBOARD_FONT_SIZE, Color::WHITE),
+                ),
+            ));
+            parent.spawn(TextBundle::from_section(
+                "Left/Right to choose, Space to start",
+                text_style(SCORE_SIZE, Color::WHITE),
+            ));
+        });
+}
+
+fn select_level(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut config: ResMut<GameConfig>,
+    mut text_query: Query<&mut Text, With<LevelSelectText>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Left) {
+        config.layout = config.layout.prev();
+        text_query.single_mut().sections[0].value = config.layout.label().to_string();
+    } else if keyboard_input.just_pressed(KeyCode::Right) {
+        config.layout = config.layout.next();
+        text_query.single_mut().sections[0].value = config.layout.label().to_string();
+    }
+}
+
+fn despawn_level_select_screen(
+    mut commands: Commands,
+    level_select_screen: Query<Entity, With<LevelSelectScreen>>,
+) {
+    for entity in &level_select_screen {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn apply_level_selection(config: Res<GameConfig>, mut level: ResMut<Level>) {
+    *level = config.layout.build(&config);
+}
+
+fn despawn_game_over_screen(
+    mut commands: Commands,
+    game_over_screen: Query<Entity, With<GameOverScreen>>,
+) {
+    for entity in &game_over_screen {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// Bundles the per-kind "entity with despawnable children" queries that `clear_map`
+// tears down, so the system itself stays under clippy's argument limit.
+#[derive(SystemParam)]
+struct MapEntityQueries<'w, 's> {
+    body: Query<'w, 's, (Entity, &'static Children), With<Body>>,
+    food: Query<'w, 's, (Entity, &'static Children), With<Food>>,
+    obstacles: Query<'w, 's, (Entity, &'static Children), With<Obstacle>>,
+}
+
+impl<'w, 's> MapEntityQueries<'w, 's> {
+    fn iter(&self) -> impl Iterator<Item = (Entity, &Children)> {
+        self.body
+            .iter()
+            .chain(self.food.iter())
+            .chain(self.obstacles.iter())
+    }
+}
+
 fn clear_map(
     mut commands: Commands,
-    body_query: Query<(Entity, &Children), With<Body>>,
-    apple_query: Query<(Entity, &Children), With<Apple>>,
+    map_entities: MapEntityQueries,
     mut snake: ResMut<Snake>,
     mut scoreboard: ResMut<Scoreboard>,
     mut text_query: Query<&mut Text, With<ScoreText>>,
+    mut move_timer: ResMut<MoveTimer>,
+    config: Res<GameConfig>,
 ) {
-    for (entity, children) in &body_query {
+    for (entity, children) in map_entities.iter() {
         commands.entity(entity).despawn();
         for &child in children {
             commands.entity(child).despawn();
         }
     }
 
-    let (apple_entity, children) = apple_query.single();
-    commands.entity(apple_entity).despawn();
-    for &child in children {
-        commands.entity(child).despawn();
-    }
-
     snake.clear();
 
     scoreboard.score = 0;
     let mut text = text_query.single_mut();
     text.sections[0].value = scoreboard.score.to_string();
+
+    *move_timer = MoveTimer::from_interval(config.start_interval);
 }
 
 fn enter_game(mut app_state: ResMut<NextState<AppState>>) {
     app_state.set(AppState::InGame);
 }
 
-fn eat_apple(
+fn snake_eating(
     mut commands: Commands,
-    head: Query<&Transform, With<Head>>,
-    body: Query<&Transform, With<Body>>,
-    mut apple: Query<&mut Transform, (With<Apple>, Without<Body>, Without<Head>)>,
-    mut snake: ResMut<Snake>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut growth_writer: EventWriter<GrowthEvent>,
+    head: Query<&Position, With<Head>>,
+    food: Query<(Entity, &Position, &Children), With<Food>>,
     mut scoreboard: ResMut<Scoreboard>,
     mut text_query: Query<&mut Text, With<ScoreText>>,
 ) {
-    let mut apple_pos = apple.single_mut();
     let head_pos = head.single();
 
-    let diff = (apple_pos.translation - head_pos.translation).abs();
-    if (diff.x <= f32::EPSILON) && (diff.y <= f32::EPSILON) {
-        let black_square = get_square(&mut meshes, &mut materials, SNAKE_COLOR);
+    for (food_entity, food_pos, children) in food.iter() {
+        if food_pos == head_pos {
+            commands.entity(food_entity).despawn();
+            for &child in children {
+                commands.entity(child).despawn();
+            }
 
-        let tail_id = snake.ids.last().unwrap().clone();
-        let tail_dir = snake.dirs.back().unwrap().clone();
+            scoreboard.score += 1;
+            let mut text = text_query.single_mut();
+            text.sections[0].value = scoreboard.score.to_string();
 
-        let tail_pos = body.get(tail_id).unwrap().translation;
+            growth_writer.send(GrowthEvent);
+        }
+    }
+}
+
+fn snake_growth(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut snake: ResMut<Snake>,
+    last_tail_position: Res<LastTailPosition>,
+    mut growth_reader: EventReader<GrowthEvent>,
+) {
+    for _ in growth_reader.read() {
+        let black_square = get_square(&mut meshes, &mut materials, SNAKE_COLOR);
+        let tail_pos = last_tail_position.0.unwrap();
+        let tail_dir = *snake.dirs.back().unwrap();
 
         let new_tail_id = commands
-            .spawn((
-                Body {},
-                SpatialBundle {
-                    transform: Transform::from_xyz(
-                        tail_pos.x - tail_dir.x as f32,
-                        tail_pos.y - tail_dir.y as f32,
-                        1.,
-                    ),
-                    ..Default::default()
-                },
-            ))
+            .spawn((Body {}, tail_pos, SpatialBundle::default()))
             .with_children(|parent| {
-                parent.spawn(black_square.clone());
+                parent.spawn((black_square, Size::square(SPRITE_SCALE)));
             })
             .id();
 
         snake.add_entity(new_tail_id, tail_dir);
+    }
+}
 
-        let mut rng = thread_rng();
-        let x_dist = (-WALL_SIZE..WALL_SIZE).step_by(STEP as usize).filter(|i| {
-            body.iter()
-                .map(|pos| pos.translation.x)
-                .any(|x| *i != x as i16)
-        });
-        let y_dist = (-WALL_SIZE..WALL_SIZE).step_by(STEP as usize).filter(|i| {
-            body.iter()
-                .map(|pos| pos.translation.y)
-                .any(|y| *i != y as i16)
-        });
-
-        apple_pos.translation.x = x_dist.choose(&mut rng).unwrap() as f32;
-        apple_pos.translation.y = y_dist.choose(&mut rng).unwrap() as f32;
+// Bundles the position queries `food_spawner` reads to find empty cells, so the
+// system itself stays under clippy's argument limit.
+#[derive(SystemParam)]
+struct OccupiedPositions<'w, 's> {
+    food: Query<'w, 's, &'static Position, With<Food>>,
+    body: Query<'w, 's, &'static Position, With<Body>>,
+    obstacles: Query<'w, 's, &'static Position, With<Obstacle>>,
+}
 
-        let mut text = text_query.single_mut();
-        scoreboard.score += 1;
-        text.sections[0].value = scoreboard.score.to_string();
+impl<'w, 's> OccupiedPositions<'w, 's> {
+    fn iter(&self) -> impl Iterator<Item = &Position> {
+        self.body
+            .iter()
+            .chain(self.food.iter())
+            .chain(self.obstacles.iter())
     }
 }
 
-fn change_direction(keyboard_input: Res<Input<KeyCode>>, mut parts: ResMut<Snake>) {
-    let direction = &mut parts.dirs[0];
-    if keyboard_input.pressed(KeyCode::Left) && direction.x == 0 {
-        direction.x = -STEP;
-        direction.y = 0;
+fn food_spawner(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    time: Res<Time>,
+    mut timer: ResMut<FoodSpawnTimer>,
+    occupied_queries: OccupiedPositions,
+    config: Res<GameConfig>,
+) {
+    if !timer.0.tick(time.delta()).just_finished() {
+        return;
     }
-    if keyboard_input.pressed(KeyCode::Right) && direction.x == 0 {
-        direction.x = STEP;
-        direction.y = 0;
+
+    if occupied_queries.food.iter().count() >= FOOD_CAP {
+        return;
     }
-    if keyboard_input.pressed(KeyCode::Up) && direction.y == 0 {
-        direction.x = 0;
-        direction.y = STEP;
+
+    let occupied: Vec<Position> = occupied_queries.iter().copied().collect();
+    let mut rng = thread_rng();
+    let empty_cells = (0..config.arena_width)
+        .flat_map(|x| (0..config.arena_height).map(move |y| Position { x, y }))
+        .filter(|pos| !occupied.contains(pos));
+
+    if let Some(pos) = empty_cells.choose(&mut rng) {
+        let red_square = get_square(&mut meshes, &mut materials, Color::RED);
+        commands
+            .spawn((Food {}, pos, SpatialBundle::default()))
+            .with_children(|parent| {
+                parent.spawn((red_square, Size::square(SPRITE_SCALE)));
+            });
     }
-    if keyboard_input.pressed(KeyCode::Down) && direction.y == 0 {
-        direction.x = 0;
-        direction.y = -STEP;
+}
+
+fn change_direction(keyboard_input: Res<Input<KeyCode>>, mut snake: ResMut<Snake>) {
+    let requested = if keyboard_input.pressed(KeyCode::Left) {
+        Some(Direction::Left)
+    } else if keyboard_input.pressed(KeyCode::Right) {
+        Some(Direction::Right)
+    } else if keyboard_input.pressed(KeyCode::Up) {
+        Some(Direction::Up)
+    } else if keyboard_input.pressed(KeyCode::Down) {
+        Some(Direction::Down)
+    } else {
+        None
+    };
+
+    if let Some(dir) = requested {
+        if dir != snake.heading.opposite() {
+            snake.pending = dir;
+        }
     }
 }
 
+fn adjust_move_speed(
+    scoreboard: Res<Scoreboard>,
+    config: Res<GameConfig>,
+    mut move_timer: ResMut<MoveTimer>,
+) {
+    let t = (scoreboard.score as f32 / config.apples_per_speedup as f32).min(1.0);
+    let interval = config.start_interval + t * (config.min_interval - config.start_interval);
+    move_timer.0.set_duration(Duration::from_secs_f32(interval));
+}
+
 fn move_snake(
-    mut query: Query<&mut Transform, (With<Body>, Without<Apple>)>,
-    mut parts: ResMut<Snake>,
+    mut query: Query<&mut Position, (With<Body>, Without<Food>)>,
+    mut snake: ResMut<Snake>,
+    mut last_tail_position: ResMut<LastTailPosition>,
+    time: Res<Time>,
+    mut move_timer: ResMut<MoveTimer>,
 ) {
-    for (entity, dir) in zip(&parts.ids, &parts.dirs) {
-        let mut pos = query.get_mut(*entity).unwrap();
-        pos.translation.x += dir.x as f32;
-        pos.translation.y += dir.y as f32;
+    if !move_timer.0.tick(time.delta()).just_finished() {
+        return;
     }
 
-    parts.dirs.pop_back();
-    let front = *parts.dirs.front().unwrap();
-    parts.dirs.push_front(front);
+    let heading = snake.pending;
+    snake.heading = heading;
+    snake.dirs.pop_back();
+    snake.dirs.push_front(heading);
+
+    let tail_id = *snake.ids.last().unwrap();
+    last_tail_position.0 = Some(*query.get(tail_id).unwrap());
+
+    for (entity, dir) in zip(&snake.ids, &snake.dirs) {
+        let mut pos = query.get_mut(*entity).unwrap();
+        let (dx, dy) = dir.delta();
+        pos.x += dx;
+        pos.y += dy;
+    }
 }
 
-fn wall_collision(mut snake: Query<&mut Transform, With<Body>>) {
-    let wall_size = WALL_SIZE as f32;
-    let limit = wall_size + STEP as f32;
+fn wall_collision(mut snake: Query<&mut Position, With<Body>>, config: Res<GameConfig>) {
     for mut pos in snake.iter_mut() {
-        if pos.translation.x >= limit {
-            pos.translation.x = -wall_size;
+        if pos.x >= config.arena_width {
+            pos.x = 0;
         }
-        if pos.translation.x <= -limit {
-            pos.translation.x = wall_size;
+        if pos.x < 0 {
+            pos.x = config.arena_width - 1;
         }
-        if pos.translation.y >= limit {
-            pos.translation.y = -wall_size;
+        if pos.y >= config.arena_height {
+            pos.y = 0;
         }
-        if pos.translation.y <= -limit {
-            pos.translation.y = wall_size;
+        if pos.y < 0 {
+            pos.y = config.arena_height - 1;
         }
     }
 }